@@ -1,8 +1,10 @@
-use ndarray::{Array1, Array2, Axis};
+use ndarray::{Array1, Array2, ArrayView2, Axis};
 use numpy::{PyArray2, PyReadonlyArray2};
 use pyo3::prelude::*;
 use nalgebra::{DMatrix, SymmetricEigen};
 
+type EigPairs = Vec<(f64, Array1<f64>)>;
+
 fn sample_cov(y: &Array2<f64>) -> Array2<f64> {
     let n = y.nrows() as f64;
     y.t().dot(y) / n
@@ -43,32 +45,59 @@ fn ledoit_wolf_cov<'py>(py: Python<'py>, returns: PyReadonlyArray2<f64>) -> PyRe
     Ok(PyArray2::from_owned_array(py, emp_cov))
 }
 
-#[pyfunction]
-fn pca_factor_cov<'py>(
-    py: Python<'py>,
-    returns: PyReadonlyArray2<f64>,
-    n_components: usize,
-) -> PyResult<&'py PyArray2<f64>> {
-    let x = returns.as_array();
+// Eigenpairs of the demeaned sample covariance, via the Gram-matrix fast
+// path when n < p. Also returns the demeaned returns, the sample
+// covariance, and the jitter applied (0.0 if none was needed).
+fn pca_eig_pairs(
+    x: &ArrayView2<f64>,
+) -> (EigPairs, Array2<f64>, Array2<f64>, f64) {
     let n = x.nrows();
     let p = x.ncols();
 
     let mean = x.mean_axis(Axis(0)).unwrap();
-    let y = &x - &mean.broadcast((n, p)).unwrap();
-    let s = sample_cov(&y.to_owned());
-
-    // eigen decomposition
-    let s_na = DMatrix::from_row_slice(p, p, s.as_slice().unwrap());
-    let se = SymmetricEigen::new(s_na);
-    let mut eig_pairs: Vec<(f64, Array1<f64>)> = se
-        .eigenvalues
-        .iter()
-        .zip(se.eigenvectors.column_iter())
-        .map(|(&val, vec)| (val, Array1::from_iter(vec.iter().cloned())))
-        .collect();
+    let y = (x - &mean.broadcast((n, p)).unwrap()).to_owned();
+    let s = sample_cov(&y);
+
+    // When there are far fewer observations than assets, the p×p sample
+    // covariance has rank at most n, so eigendecompose the much smaller
+    // n×n Gram matrix instead and lift the eigenvectors back to p-space.
+    let (mut eig_pairs, jitter): (EigPairs, f64) = if n < p {
+        let gram = y.dot(&y.t()) / n as f64;
+        let (eigenvalues, eigenvectors, jitter) = jittered_symmetric_eig(&gram, 1e-10);
+        let pairs = eigenvalues
+            .iter()
+            .zip(eigenvectors.columns())
+            .map(|(&val, u_k)| {
+                let u_k = u_k.to_owned();
+                let mut v_k = y.t().dot(&u_k);
+                let norm = v_k.dot(&v_k).sqrt();
+                if norm > 0.0 {
+                    v_k /= norm;
+                }
+                (val, v_k)
+            })
+            .collect();
+        (pairs, jitter)
+    } else {
+        let (eigenvalues, eigenvectors, jitter) = jittered_symmetric_eig(&s, 1e-10);
+        let pairs = eigenvalues
+            .iter()
+            .zip(eigenvectors.columns())
+            .map(|(&val, vec)| (val, vec.to_owned()))
+            .collect();
+        (pairs, jitter)
+    };
     eig_pairs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    (eig_pairs, y, s, jitter)
+}
 
-    let m = n_components.min(p);
+fn factor_cov_from_eig_pairs(
+    eig_pairs: &[(f64, Array1<f64>)],
+    s: &Array2<f64>,
+    n_components: usize,
+) -> Array2<f64> {
+    let p = s.nrows();
+    let m = n_components.min(p).min(eig_pairs.len());
     let mut loadings = Array2::<f64>::zeros((p, m));
     let mut factor_cov = Array2::<f64>::zeros((m, m));
     for i in 0..m {
@@ -77,19 +106,319 @@ fn pca_factor_cov<'py>(
     }
 
     let approx = loadings.dot(&factor_cov).dot(&loadings.t());
-    let resid = &s - &approx;
+    let resid = s - &approx;
     let mut diag = Array2::<f64>::zeros((p, p));
     for i in 0..p {
         diag[[i, i]] = resid[[i, i]];
     }
-    let cov = approx + diag;
+    approx + diag
+}
 
+#[pyfunction]
+fn pca_factor_cov<'py>(
+    py: Python<'py>,
+    returns: PyReadonlyArray2<f64>,
+    n_components: usize,
+) -> PyResult<&'py PyArray2<f64>> {
+    let x = returns.as_array();
+    let (eig_pairs, _y, s, _jitter) = pca_eig_pairs(&x);
+    let cov = factor_cov_from_eig_pairs(&eig_pairs, &s, n_components);
+    Ok(PyArray2::from_owned_array(py, cov))
+}
+
+// Same as pca_factor_cov, but also returns the jitter that had to be
+// applied to regularize a singular or near-singular covariance (0.0 if none).
+#[pyfunction]
+fn pca_factor_cov_robust<'py>(
+    py: Python<'py>,
+    returns: PyReadonlyArray2<f64>,
+    n_components: usize,
+) -> PyResult<(&'py PyArray2<f64>, f64)> {
+    let x = returns.as_array();
+    let (eig_pairs, _y, s, jitter) = pca_eig_pairs(&x);
+    let cov = factor_cov_from_eig_pairs(&eig_pairs, &s, n_components);
+    Ok((PyArray2::from_owned_array(py, cov), jitter))
+}
+
+// Factors maximizing variance relative to benchmark_cov (generalized
+// eigenproblem S v = λ B v), dropping B-null directions instead of inverting them.
+fn relative_factor_cov_matrix(
+    x: &ArrayView2<f64>,
+    b: &Array2<f64>,
+    n_components: usize,
+) -> Array2<f64> {
+    let n = x.nrows();
+    let p = x.ncols();
+
+    let mean = x.mean_axis(Axis(0)).unwrap();
+    let y = (x - &mean.broadcast((n, p)).unwrap()).to_owned();
+    let s = sample_cov(&y);
+
+    let (b_eigenvalues, b_eigenvectors) = symmetric_eig(b);
+    let tol = 1e-10;
+
+    // w whitens S into the generalized eigenbasis (columns = Q / sqrt(λ_B));
+    // w_b maps back to loadings of S itself (columns = Q * sqrt(λ_B) = B * w's columns).
+    let mut w = Array2::<f64>::zeros((p, 0));
+    let mut w_b = Array2::<f64>::zeros((p, 0));
+    for (i, &lambda) in b_eigenvalues.iter().enumerate() {
+        if lambda > tol {
+            let q_i = b_eigenvectors.column(i);
+            let sqrt_lambda = lambda.sqrt();
+            w.push_column((q_i.to_owned() / sqrt_lambda).view()).unwrap();
+            w_b.push_column((q_i.to_owned() * sqrt_lambda).view()).unwrap();
+        }
+    }
+    let k = w.ncols();
+
+    let s_tilde = w.t().dot(&s).dot(&w);
+    let (eigenvalues, eigenvectors) = symmetric_eig(&s_tilde);
+
+    let mut eig_pairs: EigPairs = eigenvalues
+        .iter()
+        .zip(eigenvectors.columns())
+        .map(|(&val, v_tilde)| (val, w_b.dot(&v_tilde)))
+        .collect();
+    eig_pairs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+    factor_cov_from_eig_pairs(&eig_pairs, &s, n_components.min(k))
+}
+
+#[pyfunction]
+fn relative_factor_cov<'py>(
+    py: Python<'py>,
+    returns: PyReadonlyArray2<f64>,
+    benchmark_cov: PyReadonlyArray2<f64>,
+    n_components: usize,
+) -> PyResult<&'py PyArray2<f64>> {
+    let x = returns.as_array();
+    let b = benchmark_cov.as_array().to_owned();
+    let cov = relative_factor_cov_matrix(&x, &b, n_components);
     Ok(PyArray2::from_owned_array(py, cov))
 }
 
+// Folds one new observation into an exponentially-weighted mean/covariance;
+// decay is the weight kept on the prior estimate.
+#[pyfunction]
+fn cov_rank1_update<'py>(
+    py: Python<'py>,
+    cov: PyReadonlyArray2<f64>,
+    mean: Vec<f64>,
+    x: Vec<f64>,
+    decay: f64,
+) -> PyResult<(&'py PyArray2<f64>, Vec<f64>)> {
+    let cov = cov.as_array().to_owned();
+    let mean = Array1::from_vec(mean);
+    let x = Array1::from_vec(x);
+
+    let d = &x - &mean;
+    let new_mean = &mean * decay + &x * (1.0 - decay);
+
+    let p = d.len();
+    let mut cross = Array2::<f64>::zeros((p, p));
+    for i in 0..p {
+        for j in 0..p {
+            cross[[i, j]] = d[i] * d[j];
+        }
+    }
+    let new_cov = cov * decay + cross * (decay * (1.0 - decay));
+
+    Ok((
+        PyArray2::from_owned_array(py, new_cov),
+        new_mean.to_vec(),
+    ))
+}
+
+// Symmetric eigendecomposition with a jittered fallback for singular or
+// near-singular inputs; returns eigenvalues, eigenvectors, and jitter applied.
+fn jittered_symmetric_eig(a: &Array2<f64>, tol: f64) -> (Array1<f64>, Array2<f64>, f64) {
+    let p = a.nrows();
+    let mean_diag = a.diag().sum() / p as f64;
+    let mut jitter = 0.0_f64;
+    let max_attempts = 8;
+
+    for attempt in 0..=max_attempts {
+        let trial = if jitter == 0.0 {
+            a.clone()
+        } else {
+            let mut t = a.clone();
+            for i in 0..p {
+                t[[i, i]] += jitter;
+            }
+            t
+        };
+        let (eigenvalues, eigenvectors) = symmetric_eig(&trial);
+        let min_eig = eigenvalues.iter().cloned().fold(f64::INFINITY, f64::min);
+        if min_eig > tol || attempt == max_attempts {
+            return (eigenvalues, eigenvectors, jitter);
+        }
+        jitter = if jitter == 0.0 {
+            1e-6 * mean_diag.abs().max(1e-12)
+        } else {
+            jitter * 10.0
+        };
+    }
+    unreachable!()
+}
+
+/// Symmetric eigendecomposition helper shared by the PSD utilities below.
+fn symmetric_eig(a: &Array2<f64>) -> (Array1<f64>, Array2<f64>) {
+    let p = a.nrows();
+    let a_na = DMatrix::from_row_slice(p, p, a.as_slice().unwrap());
+    let se = SymmetricEigen::new(a_na);
+    let eigenvalues = Array1::from_iter(se.eigenvalues.iter().cloned());
+    let mut eigenvectors = Array2::<f64>::zeros((p, p));
+    for (i, col) in se.eigenvectors.column_iter().enumerate() {
+        for (j, &v) in col.iter().enumerate() {
+            eigenvectors[[j, i]] = v;
+        }
+    }
+    (eigenvalues, eigenvectors)
+}
+
+/// Returns true if the minimum eigenvalue of `cov` exceeds `-tol`.
+fn is_psd_matrix(cov: &Array2<f64>, tol: f64) -> bool {
+    let (eigenvalues, _) = symmetric_eig(cov);
+    let min_eig = eigenvalues.iter().cloned().fold(f64::INFINITY, f64::min);
+    min_eig > -tol
+}
+
+#[pyfunction]
+fn is_psd(cov: PyReadonlyArray2<f64>, tol: f64) -> PyResult<bool> {
+    Ok(is_psd_matrix(&cov.as_array().to_owned(), tol))
+}
+
+/// Higham's alternating-projections algorithm for the nearest PSD (optionally
+/// correlation) matrix to `a` in Frobenius norm.
+fn nearest_psd(a: &Array2<f64>, corr: bool) -> Array2<f64> {
+    let p = a.nrows();
+
+    let mut y = a.clone();
+    let mut d_s = Array2::<f64>::zeros((p, p));
+
+    for _ in 0..100 {
+        let r = &y - &d_s;
+        let (eigenvalues, eigenvectors) = symmetric_eig(&r);
+        let clamped = eigenvalues.mapv(|v| v.max(0.0));
+        let mut x = Array2::<f64>::zeros((p, p));
+        for k in 0..p {
+            let v_k = eigenvectors.column(k);
+            for i in 0..p {
+                for j in 0..p {
+                    x[[i, j]] += clamped[k] * v_k[i] * v_k[j];
+                }
+            }
+        }
+        d_s = &x - &r;
+
+        let y_next = if corr {
+            let mut y_next = x.clone();
+            for i in 0..p {
+                y_next[[i, i]] = 1.0;
+            }
+            y_next
+        } else {
+            x
+        };
+
+        let diff = &y_next - &y;
+        let change = diff.mapv(|v| v * v).sum().sqrt();
+        y = y_next;
+        if change < 1e-8 {
+            break;
+        }
+    }
+
+    // symmetrize to remove residual floating-point asymmetry
+    (&y + &y.t()) * 0.5
+}
+
+#[pyfunction]
+fn nearest_psd_cov<'py>(
+    py: Python<'py>,
+    a: PyReadonlyArray2<f64>,
+    corr: bool,
+) -> PyResult<&'py PyArray2<f64>> {
+    let sym = nearest_psd(&a.as_array().to_owned(), corr);
+    Ok(PyArray2::from_owned_array(py, sym))
+}
+
 #[pymodule]
 fn covariance_rs(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(ledoit_wolf_cov, m)?)?;
     m.add_function(wrap_pyfunction!(pca_factor_cov, m)?)?;
+    m.add_function(wrap_pyfunction!(pca_factor_cov_robust, m)?)?;
+    m.add_function(wrap_pyfunction!(is_psd, m)?)?;
+    m.add_function(wrap_pyfunction!(nearest_psd_cov, m)?)?;
+    m.add_function(wrap_pyfunction!(cov_rank1_update, m)?)?;
+    m.add_function(wrap_pyfunction!(relative_factor_cov, m)?)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_psd_fixes_indefinite_matrix() {
+        // eigenvalues -1, 3: indefinite.
+        let a = Array2::from_shape_vec((2, 2), vec![1.0, 2.0, 2.0, 1.0]).unwrap();
+        assert!(!is_psd_matrix(&a, 1e-8));
+
+        let projected = nearest_psd(&a, false);
+        assert!(is_psd_matrix(&projected, 1e-8));
+    }
+
+    #[test]
+    fn nearest_psd_corr_keeps_unit_diagonal() {
+        let a = Array2::from_shape_vec((2, 2), vec![1.0, 1.5, 1.5, 1.0]).unwrap();
+        let projected = nearest_psd(&a, true);
+        assert!(is_psd_matrix(&projected, 1e-8));
+        assert!((projected[[0, 0]] - 1.0).abs() < 1e-6);
+        assert!((projected[[1, 1]] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn relative_factor_cov_matches_pca_when_benchmark_is_identity() {
+        let x = Array2::from_shape_vec(
+            (6, 3),
+            vec![
+                0.02, -0.01, 0.03, 0.01, 0.00, -0.02, -0.03, 0.02, 0.01, 0.00, 0.01, 0.02, 0.02,
+                -0.02, 0.00, -0.01, 0.03, -0.01,
+            ],
+        )
+        .unwrap();
+        let identity = Array2::<f64>::eye(3);
+
+        let relative = relative_factor_cov_matrix(&x.view(), &identity, 2);
+
+        let (eig_pairs, _y, s, _jitter) = pca_eig_pairs(&x.view());
+        let pca = factor_cov_from_eig_pairs(&eig_pairs, &s, 2);
+
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((relative[[i, j]] - pca[[i, j]]).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn relative_factor_cov_handles_low_rank_benchmark() {
+        let x = Array2::from_shape_vec(
+            (6, 3),
+            vec![
+                0.02, -0.01, 0.03, 0.01, 0.00, -0.02, -0.03, 0.02, 0.01, 0.00, 0.01, 0.02, 0.02,
+                -0.02, 0.00, -0.01, 0.03, -0.01,
+            ],
+        )
+        .unwrap();
+        // Rank-1 benchmark covariance: only one non-null direction.
+        let b =
+            Array2::from_shape_vec((3, 3), vec![1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0])
+                .unwrap();
+
+        let cov = relative_factor_cov_matrix(&x.view(), &b, 2);
+        assert_eq!(cov.dim(), (3, 3));
+        assert!(cov.iter().all(|v| v.is_finite()));
+    }
+}
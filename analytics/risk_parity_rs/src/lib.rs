@@ -1,19 +1,48 @@
 use ndarray::{Array1, ArrayView2};
 use numpy::{PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 
+/// Risk-budget weights: `budget[i]` is the desired fraction of total
+/// portfolio variance contributed by asset `i` (defaults to uniform, i.e.
+/// equal risk contribution), with per-asset bounds `w_min`/`w_max`
+/// (default `0`/`1`). Each sweep clamps the weights to their bounds and then
+/// renormalizes them to sum to 1, so the returned weights are not guaranteed
+/// to lie within the bounds.
 #[pyfunction]
-fn risk_parity_weights(cov: PyReadonlyArray2<f64>) -> PyResult<Vec<f64>> {
+#[pyo3(signature = (cov, budget=None, w_min=None, w_max=None))]
+fn risk_budget_weights(
+    cov: PyReadonlyArray2<f64>,
+    budget: Option<Vec<f64>>,
+    w_min: Option<Vec<f64>>,
+    w_max: Option<Vec<f64>>,
+) -> PyResult<Vec<f64>> {
     let cov: ArrayView2<f64> = cov.as_array();
     let n = cov.shape()[0];
+
+    let budget = budget.unwrap_or_else(|| vec![1.0 / n as f64; n]);
+    let w_min = w_min.unwrap_or_else(|| vec![0.0; n]);
+    let w_max = w_max.unwrap_or_else(|| vec![1.0; n]);
+    if budget.len() != n || w_min.len() != n || w_max.len() != n {
+        return Err(PyValueError::new_err(format!(
+            "budget/w_min/w_max must each have length {n}, got {}/{}/{}",
+            budget.len(),
+            w_min.len(),
+            w_max.len()
+        )));
+    }
+    let budget = Array1::from_vec(budget);
+    let w_min = Array1::from_vec(w_min);
+    let w_max = Array1::from_vec(w_max);
+
     let mut w = Array1::from_elem(n, 1.0 / n as f64);
 
     for _ in 0..100 {
         let port_var = w.t().dot(&cov.dot(&w));
         let mrc = cov.dot(&w);
         let rc = &w * &mrc;
-        let target = port_var / n as f64;
-        let diff = &rc - target;
+        let target = &budget * port_var;
+        let diff = &rc - &target;
         if diff
             .mapv(f64::abs)
             .iter()
@@ -26,8 +55,10 @@ fn risk_parity_weights(cov: PyReadonlyArray2<f64>) -> PyResult<Vec<f64>> {
         for i in 0..n {
             let denom = mrc[i] + 1e-12;
             w[i] -= diff[i] / denom;
-            if w[i] < 0.0 {
-                w[i] = 0.0;
+            if w[i] < w_min[i] {
+                w[i] = w_min[i];
+            } else if w[i] > w_max[i] {
+                w[i] = w_max[i];
             }
         }
         let sum_w: f64 = w.sum();
@@ -42,6 +73,6 @@ fn risk_parity_weights(cov: PyReadonlyArray2<f64>) -> PyResult<Vec<f64>> {
 
 #[pymodule]
 fn risk_parity_rs(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(risk_parity_weights, m)?)?;
+    m.add_function(wrap_pyfunction!(risk_budget_weights, m)?)?;
     Ok(())
 }